@@ -1,15 +1,270 @@
 #[macro_use] extern crate clap;
 extern crate chrono;
 extern crate indicatif;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::VecDeque;
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
 use std::io::{
     Read, Write,
     BufReader, BufWriter,
     ErrorKind};
 use std::io;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 
 const DEFAULT_BUF_SIZE: usize = 65536;
 
+/// The sliding window used to compute `-r`'s recent rate, as opposed to `-a`'s lifetime average
+const RATE_WINDOW: Duration = Duration::from_secs(3);
+
+/// Formats a per-second count, either as human-readable bytes or a raw number of lines
+fn format_rate(w: &mut dyn FmtWrite, rate: f64, line_mode: bool) {
+    if line_mode {
+        let _ = write!(w, "{:.0}/s", rate);
+    } else {
+        let _ = write!(w, "{}/s", HumanBytes(rate.max(0.0) as u64));
+    }
+}
+
+/// Drop samples older than `window` relative to `now`, keeping the sliding window used
+/// for `-r`'s recent-rate calculation bounded
+fn trim_window(samples: &mut VecDeque<(Instant, u64)>, now: Instant, window: Duration) {
+    while let Some(&(oldest, _)) = samples.front() {
+        if now.duration_since(oldest) > window {
+            samples.pop_front();
+        } else {
+            break;
+        }
+    }
+}
+
+/// Computes and formats the rate fields (`-r`'s recent windowed rate, `-a`'s lifetime
+/// average) for one progress bar. There's no per-key template callback in this indicatif
+/// version, so instead of a custom `ProgressTracker` we recompute both rates ourselves
+/// and push the result through the bar's `{msg}` slot.
+struct RateDisplay {
+    line_mode: bool,
+    show_rate: bool,
+    show_avg_rate: bool,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl RateDisplay {
+    fn new(line_mode: bool, show_rate: bool, show_avg_rate: bool) -> Self {
+        RateDisplay { line_mode, show_rate, show_avg_rate, samples: VecDeque::new() }
+    }
+
+    fn active(&self) -> bool {
+        self.show_rate || self.show_avg_rate
+    }
+
+    /// Recompute the enabled rate(s) for the given position/elapsed time and format
+    /// them as the text that should go in `{msg}`
+    fn update(&mut self, pos: u64, elapsed: Duration) -> String {
+        let now = Instant::now();
+        let mut out = String::new();
+        if self.show_rate {
+            self.samples.push_back((now, pos));
+            trim_window(&mut self.samples, now, RATE_WINDOW);
+            let cur_rate = self.samples.front().and_then(|&(oldest, oldest_pos)| {
+                let dt = now.duration_since(oldest).as_secs_f64();
+                if dt > 0.0 { Some((pos - oldest_pos) as f64 / dt) } else { None }
+            }).unwrap_or(0.0);
+            format_rate(&mut out, cur_rate, self.line_mode);
+        }
+        if self.show_avg_rate {
+            if !out.is_empty() {
+                out.push(' ');
+            }
+            let secs = elapsed.as_secs_f64();
+            let avg_rate = if secs > 0.0 { pos as f64 / secs } else { 0.0 };
+            format_rate(&mut out, avg_rate, self.line_mode);
+        }
+        out
+    }
+}
+
+/// Parse a rate-limit argument like `1M`, `512K`, or a plain byte count into bytes/sec.
+/// Rejects anything that isn't a strictly positive number, since a zero or negative
+/// rate would divide by zero (or sleep a negative duration) in `RateLimiter::acquire`.
+fn parse_rate(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let (num, mult) = match s.chars().last() {
+        Some('k') | Some('K') => (&s[..s.len() - 1], 1024.0),
+        Some('m') | Some('M') => (&s[..s.len() - 1], 1024.0 * 1024.0),
+        Some('g') | Some('G') => (&s[..s.len() - 1], 1024.0 * 1024.0 * 1024.0),
+        _ => (s, 1.0),
+    };
+    num.trim().parse::<f64>().ok()
+        .map(|n| n * mult)
+        .filter(|n| n.is_finite() && *n > 0.0)
+}
+
+/// clap validator for `-L`/`--rate-limit`: surfaces malformed or non-positive rates as a
+/// proper usage error instead of silently disabling the limiter
+fn validate_rate(s: String) -> Result<(), String> {
+    parse_rate(&s)
+        .map(|_| ())
+        .ok_or_else(|| format!("invalid rate limit '{}': expected a positive number with an optional K/M/G suffix", s))
+}
+
+/// A token-bucket limiter used to throttle the copy loop to a target rate (bytes/sec)
+struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    burst: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate: f64) -> Self {
+        let burst = DEFAULT_BUF_SIZE as f64;
+        RateLimiter {
+            rate,
+            tokens: burst,
+            burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Refill tokens based on elapsed time, then return how many bytes may be read now.
+    /// When the bucket is dry, sleep for a whole buffer's worth of tokens (capped by
+    /// `burst`) rather than just one byte, so throttled transfers stay batched instead
+    /// of degrading into a byte-at-a-time read()/write() loop.
+    fn acquire(&mut self, want: usize) -> usize {
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.tokens = (self.tokens + self.rate * dt).min(self.burst);
+
+        if self.tokens < 1.0 {
+            let target = (want as f64).min(self.burst).max(1.0);
+            let needed = target - self.tokens;
+            thread::sleep(std::time::Duration::from_secs_f64(needed / self.rate));
+            self.tokens += self.rate * (needed / self.rate);
+        }
+
+        (want as f64).min(self.tokens.floor()).max(1.0) as usize
+    }
+
+    /// Account for bytes actually sent
+    fn consume(&mut self, sent: u64) {
+        self.tokens -= sent as f64;
+    }
+}
+
+/// Parse a `-i`/`--interval` value in seconds, rejecting anything negative or non-finite
+/// (a negative value would make `Duration::from_secs_f64` panic at startup). `0` is
+/// accepted and means "no throttling", handled by the caller rather than here.
+fn parse_interval(s: &str) -> Option<f64> {
+    s.trim().parse::<f64>().ok().filter(|n| n.is_finite() && *n >= 0.0)
+}
+
+/// clap validator for `-i`/`--interval`: surfaces a malformed or negative interval as a
+/// proper usage error instead of panicking when the `Throttle` is constructed
+fn validate_interval(s: String) -> Result<(), String> {
+    parse_interval(&s)
+        .map(|_| ())
+        .ok_or_else(|| format!("invalid interval '{}': expected a non-negative number of seconds", s))
+}
+
+/// Gates progress-bar redraws to at most once per `interval`, in the style of Cargo's
+/// progress throttling. The first update is always let through; callers are expected to
+/// flush once more unconditionally after the copy loop ends.
+struct Throttle {
+    interval: Duration,
+    last_update: Option<Instant>,
+}
+
+impl Throttle {
+    fn new(interval: Duration) -> Self {
+        Throttle { interval, last_update: None }
+    }
+
+    fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        let due = self.last_update.map_or(true, |last| now.duration_since(last) >= self.interval);
+        if due {
+            self.last_update = Some(now);
+        }
+        due
+    }
+}
+
+/// Plain numeric progress for `-n`: prints percent complete (or a raw byte count, if the
+/// size isn't known) to stderr, one value per line, only when the value actually changes
+struct NumericProgress {
+    len: Option<u64>,
+    last: Option<i64>,
+}
+
+impl NumericProgress {
+    fn new(len: Option<u64>) -> Self {
+        NumericProgress { len, last: None }
+    }
+
+    fn update(&mut self, bytes: u64) {
+        let value = match self.len {
+            Some(len) if len > 0 => ((bytes as f64 / len as f64) * 100.0).round() as i64,
+            _ => bytes as i64
+        };
+        if self.last != Some(value) {
+            eprintln!("{}", value);
+            self.last = Some(value);
+        }
+    }
+}
+
+/// `isatty(3)` on the given fd, without pulling in a terminal-detection crate
+#[cfg(unix)]
+fn fd_is_tty(fd: i32) -> bool {
+    extern "C" {
+        fn isatty(fd: i32) -> i32;
+    }
+    unsafe { isatty(fd) != 0 }
+}
+
+#[cfg(not(unix))]
+fn fd_is_tty(_fd: i32) -> bool {
+    true
+}
+
+const STDERR_FILENO: i32 = 2;
+
+/// Whether a live, redrawing progress bar is appropriate here: a real terminal, not
+/// `TERM=dumb`, and not obviously running under CI. Scripts and CI logs get the bar
+/// hidden instead of mangled with carriage returns.
+fn interactive_output() -> bool {
+    if env::var("TERM").map(|t| t == "dumb").unwrap_or(false) {
+        return false;
+    }
+    if env::var_os("CI").is_some() {
+        return false;
+    }
+    fd_is_tty(STDERR_FILENO)
+}
+
+/// Open an input file, or print a short diagnostic and exit non-zero instead of panicking
+/// with a backtrace when the path doesn't exist or isn't readable
+fn open_input(path: &str) -> File {
+    File::open(path).unwrap_or_else(|e| {
+        eprintln!("pv: {}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+/// The prefix label for one bar when pv is given multiple `INPUT` files: `NAME: path`
+/// when `-N` was given, otherwise just the path
+fn label_for(path: &str, name: Option<&str>) -> String {
+    match name {
+        Some(n) => format!("{}: {}", n, path),
+        None => path.to_string()
+    }
+}
+
 fn main() {
     let matches = clap_app!(pv =>
         (version: "0.1.0")
@@ -20,13 +275,17 @@ fn main() {
         (@arg width: -w --width +takes_value "Width of the progressbar (default: max)")
         (@arg bytes: -b --bytes "Show number of bytes transferred")
         (@arg rate: -r --rate "Show data transfer rate counter")
-        (@arg average_rate: -a --("average-rate") "Show data transfer average rate counter (same as rate in this implementation, for now)")
+        (@arg average_rate: -a --("average-rate") "Show data transfer average rate counter (lifetime average, distinct from -r's recent windowed rate)")
         (@arg eta: -e --eta "Show estimated time of arrival (completion)")
         (@arg line_mode: -l --("line-mode") "Count lines instead of bytes")
         (@arg null: --null "Lines are null-terminated") // TODO: need to support -0
         (@arg skip_input_errors: -E --("skip-errors") "Skip read errors in input")
         (@arg skip_output_errors: --("skip-output-errors") "Skip read errors in output")
-        //(@arg INPUT: ... "Input filenames")
+        (@arg rate_limit: -L --("rate-limit") +takes_value {validate_rate} "Limit transfer to RATE bytes per second (accepts K/M/G suffixes)")
+        (@arg interval: -i --interval +takes_value {validate_interval} "Only redraw the progress bar at most once per INTERVAL seconds")
+        (@arg numeric: -n --numeric "Output percentages (or raw byte counts if size is unknown) instead of a bar, for driving external GUIs")
+        (@arg name: -N --name +takes_value "Prefix the progress bar(s) with this label")
+        (@arg INPUT: +multiple "Input filenames (if omitted, read from stdin); concatenated in order")
 
         // These are not really a priority
         (@arg buffer_percent: -T --("buffer-percent") "Ignored for compatibility")
@@ -34,25 +293,95 @@ fn main() {
         (@arg quiet: -q --quiet "Ignored for compatibility; if you want \"quiet\", don't use pv")
         (@arg progress: -p --progress "Ignored for compatibility; this implementation always shows the progressbar")
     ).get_matches();
+
+    let show_timer = matches.is_present("timer");
+    let width = matches.value_of("width").and_then(|x| x.parse().ok());
+    let show_bytes = matches.is_present("bytes");
+    let show_eta = matches.is_present("eta");
+    let show_rate = matches.is_present("rate");
+    let show_avg_rate = matches.is_present("average_rate");
+    let line_mode = matches.is_present("line_mode");
+    let explicit_size: Option<u64> = matches.value_of("size").and_then(|x| x.parse().ok());
+    let name = matches.value_of("name");
+    let numeric = matches.is_present("numeric");
+    // In numeric mode we drive stderr ourselves; don't also render a live bar
+    let show_bar = interactive_output() && !numeric;
+
+    let bar_opts = BarOptions {
+        show_timer, width, show_bytes, show_eta, show_rate, show_avg_rate, line_mode,
+        interactive: show_bar
+    };
+    // With no display flags at all, progress_from_options falls back to a default
+    // template that always shows the windowed rate; keep RateDisplay in sync with that
+    let default_display = !(show_timer || show_bytes || show_rate || show_avg_rate || show_eta);
+    let show_rate_effective = show_rate || default_display;
+
+    let input_files: Vec<&str> = matches.values_of("INPUT")
+        .map(|v| v.collect())
+        .unwrap_or_default();
+
+    // Build one reader per input (or a single stdin reader), each with its own
+    // progress bar. When there's more than one, a MultiProgress ties their bars
+    // together on screen along with an aggregate "total" bar.
+    let (sources, total, overall_len) : (Vec<NamedSource>, Option<ProgressBar>, Option<u64>) = if input_files.len() <= 1 {
+        let (label, len, reader): (String, Option<u64>, Box<dyn Read>) = match input_files.first() {
+            Some(path) => (
+                name.unwrap_or(path).to_string(),
+                explicit_size.or_else(|| std::fs::metadata(path).ok().map(|m| m.len())),
+                Box::new(BufReader::new(open_input(path)))
+            ),
+            None => (
+                name.unwrap_or("stdin").to_string(),
+                explicit_size,
+                Box::new(BufReader::new(io::stdin()))
+            )
+        };
+        let bar = PipeView::progress_from_options(len, name.is_some(), &bar_opts);
+        bar.set_prefix(label);
+        let rates = RateDisplay::new(line_mode, show_rate_effective, show_avg_rate);
+        (vec![NamedSource { reader, bar, rates }], None, len)
+    } else {
+        let multi = MultiProgress::new();
+        let mut sources = Vec::with_capacity(input_files.len());
+        let mut total_len: Option<u64> = Some(0);
+        for path in &input_files {
+            let flen = std::fs::metadata(path).ok().map(|m| m.len());
+            total_len = total_len.and_then(|acc| flen.map(|l| acc + l));
+            let bar = multi.add(PipeView::progress_from_options(flen, true, &bar_opts));
+            bar.set_prefix(label_for(path, name));
+            let reader = Box::new(BufReader::new(open_input(path)));
+            let rates = RateDisplay::new(line_mode, show_rate_effective, show_avg_rate);
+            sources.push(NamedSource { reader, bar, rates });
+        }
+        let overall_len = explicit_size.or(total_len);
+        let total_bar = multi.add(PipeView::progress_from_options(overall_len, true, &bar_opts));
+        total_bar.set_prefix("total");
+        (sources, Some(total_bar), overall_len)
+    };
+
     PipeView {
-        source: Box::new(BufReader::new(io::stdin())), // Source
+        sources,
         sink: Box::new(BufWriter::new(io::stdout())),   // Sink
-        progress: PipeView::progress_from_options(
-            matches.value_of("size").and_then(|x| x.parse().ok()), // Estimated size
-            matches.is_present("timer"),        // Whether to show Elapsed Time
-            matches.value_of("width").and_then(|x| x.parse().ok()), // Progressbar width
-            matches.is_present("bytes"),        // Whether to show transferred Bytes
-            matches.is_present("eta"),          // Whether to show ETA
-            matches.is_present("rate") || matches.is_present("average_rate"),         // Whether to show the rate. TODO: Show average rate separately
-            matches.is_present("line_mode"),    // Whether to work by lines instead
-        ),
+        total_rates: total.as_ref().map(|_| RateDisplay::new(line_mode, show_rate_effective, show_avg_rate)),
+        total,
         line_mode: if matches.is_present("line_mode") {
             LineMode::Line(if matches.is_present("null") { 0 } else { 10 }) // default to unix newline
         } else {
             LineMode::Byte
         },
         skip_input_errors: matches.is_present("skip_input_errors"),
-        skip_output_errors: matches.is_present("skip_output_errors")
+        skip_output_errors: matches.is_present("skip_output_errors"),
+        // clap's validator already rejected anything negative or malformed; `0` means
+        // "no throttling" rather than a zero-length `Duration` that happens to work
+        throttle: matches.value_of("interval")
+            .map(|x| parse_interval(x).expect("validated by clap"))
+            .filter(|&secs| secs > 0.0)
+            .map(|secs| Throttle::new(Duration::from_secs_f64(secs))),
+        numeric: if numeric { Some(NumericProgress::new(overall_len)) } else { None },
+        // clap's validator already rejected non-positive/malformed rates
+        rate_limiter: matches.value_of("rate_limit")
+            .map(|x| parse_rate(x).expect("validated by clap"))
+            .map(RateLimiter::new)
     }.pipeview().unwrap();
 }
 
@@ -60,56 +389,81 @@ enum LineMode {
     Line(u8),
     Byte
 }
+
+/// One input stream paired with the progress bar that tracks it, and the rate display
+/// state (`-r`/`-a`) for that bar
+struct NamedSource {
+    reader: Box<dyn Read>,
+    bar: ProgressBar,
+    rates: RateDisplay
+}
+
+/// Display toggles shared by every progress bar `pv` creates, grouped into one struct
+/// instead of threading a long list of booleans through `progress_from_options`
+struct BarOptions {
+    show_timer: bool,
+    width: Option<usize>,
+    show_bytes: bool,
+    show_eta: bool,
+    show_rate: bool,
+    show_avg_rate: bool,
+    line_mode: bool,
+    interactive: bool
+}
+
 struct PipeView {
-    source: Box<dyn Read>,
+    sources: Vec<NamedSource>,
     sink: Box<dyn Write>,
-    progress: ProgressBar,
+    total: Option<ProgressBar>,
+    total_rates: Option<RateDisplay>,
     line_mode: LineMode,
     skip_input_errors: bool,
-    skip_output_errors: bool
+    skip_output_errors: bool,
+    rate_limiter: Option<RateLimiter>,
+    throttle: Option<Throttle>,
+    numeric: Option<NumericProgress>
 }
 
 impl PipeView {
-    /// Set up the progress bar from the parsed CLI options
-    fn progress_from_options(
-        len: Option<u64>,
-        show_timer: bool,
-        width: Option<usize>,
-        show_bytes: bool,
-        show_eta: bool,
-        show_rate: bool,
-        line_mode: bool
-    ) -> ProgressBar {
+    /// Set up the progress bar from the parsed CLI options. `-r`/`-a`'s rate fields are
+    /// driven through `{msg}`, since there's no per-key template callback to hook here;
+    /// see `RateDisplay`.
+    fn progress_from_options(len: Option<u64>, with_prefix: bool, opts: &BarOptions) -> ProgressBar {
         // What to show, from left to right, in the progress bar
         let mut template = vec![];
-        if show_timer {
+        if with_prefix {
+            template.push("{prefix}".to_string());
+        }
+        if opts.show_timer {
             template.push("{elapsed_precise}".to_string());
         }
 
-        match width {
+        match opts.width {
             Some(x) => template.push(format!("{{bar:{}}} {{percent}}", x)),
             None => template.push("{wide_bar} {percent}".to_string())
         }
 
         // Choose whether you want bytes or plain counts on several fields
-        let (pos_name, len_name, per_sec_name) = if line_mode {
-            ("{pos}", "{len}", "{per_sec}")
+        let (pos_name, len_name) = if opts.line_mode {
+            ("{pos}", "{len}")
         } else {
-            ("{bytes}", "{total_bytes}", "{bytes_per_sec}")
+            ("{bytes}", "{total_bytes}")
         };
 
         // Put the transferred and total together so they don't have a space
-        if show_bytes && len.is_some() {
+        if opts.show_bytes && len.is_some() {
             template.push(format!("{}/{}", pos_name, len_name));
-        } else if show_bytes {
+        } else if opts.show_bytes {
             template.push(pos_name.to_string());
         }
 
-        if show_rate {
-            template.push(per_sec_name.to_string());
+        // {msg} carries whichever of -r's windowed rate and -a's lifetime average are
+        // enabled, recomputed and pushed in by `RateDisplay` on every redraw
+        if opts.show_rate || opts.show_avg_rate {
+            template.push("{msg}".to_string());
         }
-        
-        if show_eta {
+
+        if opts.show_eta {
             template.push("{eta_precise}".to_string());
         }
 
@@ -120,10 +474,11 @@ impl PipeView {
 
         // Okay, that's all fine and dandy but if they don't specify anything,
         // we should have a nicer default than all empty
-        if !(show_timer || show_bytes || show_rate || show_eta) {
+        if !(opts.show_timer || opts.show_bytes || opts.show_rate || opts.show_avg_rate || opts.show_eta) {
+            let prefix = if with_prefix { "{prefix} " } else { "" };
             style = style.template(&format!(
-                "{{elapsed}} {{wide_bar}} {{percent}} {}/{} {} {{eta}}",
-                pos_name, len_name, per_sec_name)
+                "{}{{elapsed}} {{wide_bar}} {{percent}} {}/{} {{msg}} {{eta}}",
+                prefix, pos_name, len_name)
             );
         } else {
             style = style.template(&template.join(" "));
@@ -133,37 +488,191 @@ impl PipeView {
             Some(x) => ProgressBar::new(x),
             None => ProgressBar::new_spinner()
         };
-        
+
         progress.set_style(style);
+        if !opts.interactive {
+            // Not a real terminal (piped, TERM=dumb, or CI): don't spam control characters
+            progress.set_draw_target(ProgressDrawTarget::hidden());
+        }
         progress
     }
 
     fn pipeview(&mut self) -> Result<u64, Box<dyn ::std::error::Error>> {
-        // Essentially std::io::copy
+        // Essentially std::io::copy, run once per source in order
         let mut buf = [0; DEFAULT_BUF_SIZE];
         let mut written : u64 = 0;
-        loop {
-            // Always skip interruptions, maybe skip other errors
-            // Also maybe finish if we read nothing
-            let len = match self.source.read(&mut buf) {
-                Ok(0) => return Ok(written),
-                Ok(len) => len,
-                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                Err(_) if self.skip_input_errors => continue,
-                Err(e) => return Err(e.into()),
-            };
-
-            // Maybe skip output errors
-            match self.sink.write_all(&buf[..len]) {
-                Ok(_) => (),
-                Err(_) if self.skip_output_errors => continue,
-                Err(e) => return Err(e.into())
-            };
-            match self.line_mode {
-                LineMode::Line(delim) => self.progress.inc(buf[..len].iter().filter(|b| **b == delim).count() as u64),
-                LineMode::Byte => self.progress.inc(len as u64)
-            };
-            written += len as u64;
+        for source in self.sources.iter_mut() {
+            // Bytes (or lines, in line mode) advanced since the last time we actually redrew the bar(s)
+            let mut pending: u64 = 0;
+            loop {
+                // If rate-limited, only ask for as many bytes as the token bucket allows
+                let read_cap = match &mut self.rate_limiter {
+                    Some(limiter) => limiter.acquire(buf.len()),
+                    None => buf.len()
+                };
+
+                // Always skip interruptions, maybe skip other errors
+                // Also maybe finish if we read nothing
+                let len = match source.reader.read(&mut buf[..read_cap]) {
+                    Ok(0) => break,
+                    Ok(len) => len,
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) if self.skip_input_errors => continue,
+                    Err(e) => return Err(e.into()),
+                };
+
+                // Maybe skip output errors
+                match self.sink.write_all(&buf[..len]) {
+                    Ok(_) => (),
+                    Err(_) if self.skip_output_errors => continue,
+                    Err(e) => return Err(e.into())
+                };
+
+                if let Some(limiter) = &mut self.rate_limiter {
+                    limiter.consume(len as u64);
+                }
+                pending += match self.line_mode {
+                    LineMode::Line(delim) => buf[..len].iter().filter(|b| **b == delim).count() as u64,
+                    LineMode::Byte => len as u64
+                };
+                written += len as u64;
+
+                if let Some(numeric) = &mut self.numeric {
+                    numeric.update(written);
+                }
+
+                // Only redraw once per -i INTERVAL; otherwise keep the old always-redraw behavior
+                let redraw = match &mut self.throttle {
+                    Some(throttle) => throttle.allow(),
+                    None => true
+                };
+                if redraw {
+                    source.bar.inc(pending);
+                    if source.rates.active() {
+                        let msg = source.rates.update(source.bar.position(), source.bar.elapsed());
+                        source.bar.set_message(&msg);
+                    }
+                    if let Some(total) = &self.total {
+                        total.inc(pending);
+                        if let Some(rates) = self.total_rates.as_mut().filter(|r| r.active()) {
+                            let msg = rates.update(total.position(), total.elapsed());
+                            total.set_message(&msg);
+                        }
+                    }
+                    pending = 0;
+                }
+            }
+            // Always flush the final partial update, even if the throttle says no
+            if pending > 0 {
+                source.bar.inc(pending);
+                if source.rates.active() {
+                    let msg = source.rates.update(source.bar.position(), source.bar.elapsed());
+                    source.bar.set_message(&msg);
+                }
+                if let Some(total) = &self.total {
+                    total.inc(pending);
+                    if let Some(rates) = self.total_rates.as_mut().filter(|r| r.active()) {
+                        let msg = rates.update(total.position(), total.elapsed());
+                        total.set_message(&msg);
+                    }
+                }
+            }
+            source.bar.finish();
         }
+        if let Some(total) = &self.total {
+            total.finish();
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rate_accepts_plain_and_suffixed_values() {
+        assert_eq!(parse_rate("512"), Some(512.0));
+        assert_eq!(parse_rate("1k"), Some(1024.0));
+        assert_eq!(parse_rate("1M"), Some(1024.0 * 1024.0));
+        assert_eq!(parse_rate("2G"), Some(2.0 * 1024.0 * 1024.0 * 1024.0));
+    }
+
+    #[test]
+    fn parse_rate_rejects_non_positive_and_malformed_values() {
+        assert_eq!(parse_rate("0"), None);
+        assert_eq!(parse_rate("-1"), None);
+        assert_eq!(parse_rate("-1M"), None);
+        assert_eq!(parse_rate("1MB"), None);
+        assert_eq!(parse_rate("garbage"), None);
+    }
+
+    #[test]
+    fn rate_limiter_never_panics_on_a_valid_rate() {
+        // Takes the whole initial burst from a limiter built with a valid rate; zero or
+        // negative rates, which would divide by zero once the bucket is dry, never reach
+        // RateLimiter because parse_rate rejects them (see the test above).
+        let mut limiter = RateLimiter::new(parse_rate("1k").unwrap());
+        let granted = limiter.acquire(DEFAULT_BUF_SIZE);
+        limiter.consume(granted as u64);
+        assert!(granted >= 1);
+    }
+
+    #[test]
+    fn trim_window_drops_samples_older_than_the_window() {
+        let t0 = Instant::now();
+        let mut samples = VecDeque::new();
+        samples.push_back((t0, 0));
+        samples.push_back((t0 + Duration::from_secs(1), 100));
+        samples.push_back((t0 + Duration::from_secs(4), 400));
+
+        trim_window(&mut samples, t0 + Duration::from_secs(4), RATE_WINDOW);
+
+        // The t0 sample is 4s old (> the 3s window) and gets dropped; the rest survive
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples.front().unwrap().1, 100);
+    }
+
+    #[test]
+    fn parse_interval_accepts_zero_and_rejects_negative() {
+        // 0 is the "no throttling" sentinel, not an error; negative values would
+        // otherwise panic building a Duration in Throttle::new.
+        assert_eq!(parse_interval("0"), Some(0.0));
+        assert_eq!(parse_interval("0.5"), Some(0.5));
+        assert_eq!(parse_interval("-1"), None);
+        assert_eq!(parse_interval("garbage"), None);
+    }
+
+    #[test]
+    fn throttle_allows_first_update_then_gates_until_interval_elapses() {
+        let mut throttle = Throttle::new(Duration::from_millis(20));
+        assert!(throttle.allow());
+        assert!(!throttle.allow());
+        thread::sleep(Duration::from_millis(25));
+        assert!(throttle.allow());
+    }
+
+    #[test]
+    fn numeric_progress_tracks_last_only_when_the_rounded_value_changes() {
+        let mut progress = NumericProgress::new(Some(1000));
+        progress.update(500); // 50.0% -> 50
+        assert_eq!(progress.last, Some(50));
+        progress.update(504); // 50.4% still rounds to 50, no change
+        assert_eq!(progress.last, Some(50));
+        progress.update(506); // 50.6% rounds to 51, changes
+        assert_eq!(progress.last, Some(51));
+    }
+
+    #[test]
+    fn numeric_progress_falls_back_to_raw_bytes_without_a_known_size() {
+        let mut progress = NumericProgress::new(None);
+        progress.update(42);
+        assert_eq!(progress.last, Some(42));
+    }
+
+    #[test]
+    fn label_for_prefixes_with_name_only_when_given() {
+        assert_eq!(label_for("a.txt", None), "a.txt");
+        assert_eq!(label_for("a.txt", Some("stage1")), "stage1: a.txt");
     }
 }